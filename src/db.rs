@@ -9,6 +9,97 @@ pub type Result<T> = std::result::Result<T, DbError>;
 pub trait DbConnection: Sized {
     type Params: Clone;
     fn establish(params: Self::Params) -> Result<DbConnectionGuard<Self>>;
+
+    /// Attaches an [Instrumentation] sink to this connection. Because the connection is
+    /// shared via [DbConnectionGuard]'s `Rc<RefCell<>>`, every database abstraction built
+    /// on top of it (e.g. a `SortitionDb` + `MarfTrieDb` pair) emits to the same sink.
+    fn set_instrumentation<I>(&mut self, instrumentation: I)
+    where
+        I: Instrumentation<Self> + 'static;
+
+    /// Returns the [Instrumentation] sink currently attached to this connection. This is
+    /// the seam a `SortitionDb`/`MarfTrieDb`-style impl should report through instead of
+    /// reaching for its own `eprintln!`, so swapping the sink (e.g. for a metrics
+    /// collector) silences or redirects every "marf-ed" database at once.
+    fn instrumentation(&self) -> &dyn Instrumentation<Self>;
+
+    /// Like [DbConnection::establish], but attaches `instrumentation` to the connection
+    /// and reports the establish via [Instrumentation::on_establish]. [DbConnection::establish]
+    /// always opens with a [NoopInstrumentation] sink, so calling
+    /// [DbConnection::set_instrumentation] on the guard it returns is already too late for
+    /// `on_establish` to fire - this is the seam to use instead when `on_establish` needs to
+    /// observe real connections being opened (e.g. for metrics).
+    fn establish_with_instrumentation<I>(
+        params: Self::Params,
+        instrumentation: I,
+    ) -> Result<DbConnectionGuard<Self>>
+    where
+        I: Instrumentation<Self> + 'static,
+    {
+        let guard = Self::establish(params.clone())?;
+        instrumentation.on_establish(&params);
+        guard.db.borrow_mut().set_instrumentation(instrumentation);
+        Ok(guard)
+    }
+}
+
+/// Pluggable observability hook for a [DbConnection] and its [DbTransaction]s. All
+/// methods have no-op default bodies, so an implementation only needs to override the
+/// events it actually cares about (metrics, slow-query logging, etc), instead of every
+/// impl scattering its own ad hoc `eprintln!` calls.
+pub trait Instrumentation<DB>
+where
+    DB: DbConnection,
+{
+    fn on_establish(&self, _params: &DB::Params) {}
+    fn on_begin_transaction(&self) {}
+    fn on_commit(&self) {}
+    fn on_rollback(&self) {}
+    fn on_query(&self, _sql: &str, _duration: std::time::Duration) {}
+
+    /// Reports an application-level event that isn't covered by one of the other
+    /// `on_*` hooks (e.g. a `SortitionDb`/`MarfTrieDb` impl marking entry into one of
+    /// its own operations), so those impls have somewhere to report through instead of
+    /// reaching for their own `eprintln!`.
+    fn on_event(&self, _label: &str) {}
+}
+
+/// Default [Instrumentation] that does nothing. Used when no sink has been configured.
+pub struct NoopInstrumentation;
+
+impl<DB> Instrumentation<DB> for NoopInstrumentation where DB: DbConnection {}
+
+/// An [Instrumentation] that writes every event to stderr, useful for local debugging
+/// without pulling in a full metrics/tracing stack.
+pub struct StderrInstrumentation;
+
+impl<DB> Instrumentation<DB> for StderrInstrumentation
+where
+    DB: DbConnection,
+{
+    fn on_establish(&self, _params: &DB::Params) {
+        eprintln!("[db] establish");
+    }
+
+    fn on_begin_transaction(&self) {
+        eprintln!("[db] begin transaction");
+    }
+
+    fn on_commit(&self) {
+        eprintln!("[db] commit");
+    }
+
+    fn on_rollback(&self) {
+        eprintln!("[db] rollback");
+    }
+
+    fn on_query(&self, sql: &str, duration: std::time::Duration) {
+        eprintln!("[db] query ({duration:?}): {sql}");
+    }
+
+    fn on_event(&self, label: &str) {
+        eprintln!("[db] event: {label}");
+    }
 }
 
 /// Wrapper around a database connection which stores the connection in an
@@ -49,6 +140,100 @@ where
     }
 }
 
+/// Convenience forwarding of [SimpleConnection::batch_execute] onto the guard, so callers
+/// don't need to `.borrow()` the underlying `Rc<RefCell<>>` themselves just to bootstrap a
+/// schema.
+impl<DB> DbConnectionGuard<DB>
+where
+    DB: DbConnection + SimpleConnection,
+{
+    pub fn batch_execute(&self, sql: &str) -> Result<()> {
+        self.db.borrow().batch_execute(sql)
+    }
+}
+
+/// Trait for database implementations which can run a block of multiple, unparameterized
+/// SQL statements in one call (migrations, schema bootstrap), as opposed to the single
+/// parameterized statements a query path would use.
+pub trait SimpleConnection {
+    fn batch_execute(&self, sql: &str) -> Result<()>;
+}
+
+/// Trait for database implementations which can bootstrap themselves in one call: establish
+/// a connection and, if given one, apply a schema to it. This is what lets a `:memory:`
+/// database used in tests go from nothing to ready-to-query.
+pub trait DbInit: DbConnection + SimpleConnection {
+    /// Establishes a connection and, if `schema` is given, runs it as a single
+    /// multi-statement batch via [SimpleConnection::batch_execute] before returning.
+    #[allow(dead_code)]
+    fn init(params: Self::Params, schema: Option<&str>) -> Result<DbConnectionGuard<Self>> {
+        let guard = Self::establish(params)?;
+
+        if let Some(schema) = schema {
+            guard.batch_execute(schema)?;
+        }
+
+        Ok(guard)
+    }
+
+    /// Like [DbInit::init], but attaches `instrumentation` via
+    /// [DbConnection::establish_with_instrumentation] so [Instrumentation::on_establish]
+    /// observes the connection being opened.
+    fn init_with_instrumentation<I>(
+        params: Self::Params,
+        schema: Option<&str>,
+        instrumentation: I,
+    ) -> Result<DbConnectionGuard<Self>>
+    where
+        I: Instrumentation<Self> + 'static,
+    {
+        let guard = Self::establish_with_instrumentation(params, instrumentation)?;
+
+        if let Some(schema) = schema {
+            guard.batch_execute(schema)?;
+        }
+
+        Ok(guard)
+    }
+}
+
+impl<DB> DbInit for DB where DB: DbConnection + SimpleConnection {}
+
+/// Trait for database implementations which cache prepared statements per physical
+/// connection, keyed by SQL text, so hot paths that re-issue the same parameterized query
+/// (MARF and sortition lookups in particular) don't re-prepare it every time. The cache
+/// lives on the connection itself, not on a [DbTransactionGuard] or [DbConnectionGuard],
+/// since it needs to survive across transactions.
+pub trait CachingDb {
+    /// The prepared statement handle returned by [CachingDb::prepare_cached].
+    type Statement<'conn>
+    where
+        Self: 'conn;
+
+    /// Returns a prepared statement for `sql`, reusing a cached one when available. When
+    /// the cache capacity is 0, this bypasses the cache and prepares a fresh statement.
+    /// Implementations should report the lookup via [Instrumentation::on_query].
+    fn prepare_cached(&self, sql: &str) -> Result<Self::Statement<'_>>;
+
+    /// Sets how many prepared statements are kept around for this connection. `0` disables
+    /// caching entirely, falling back to preparing a fresh statement on every call.
+    fn set_statement_cache_capacity(&mut self, capacity: usize);
+}
+
+/// Start mode for a new transaction, mirroring SQLite's `BEGIN DEFERRED` /
+/// `BEGIN IMMEDIATE` / `BEGIN EXCLUSIVE`. Passed to [TransactionalDb::transaction_with];
+/// [TransactionalDb::transaction] always starts with [TransactionBehavior::Deferred].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransactionBehavior {
+    /// No locks are taken until the transaction first reads or writes. This is the default.
+    Deferred,
+    /// The write lock is taken immediately, before the transaction runs.
+    Immediate,
+    /// The write lock is taken immediately and no other connection may read or write
+    /// for the duration of the transaction.
+    Exclusive,
+}
+
 /// Trait for database implementations which support transactions.
 pub trait TransactionalDb
 where
@@ -58,13 +243,106 @@ where
     where
         Self: 'conn;
 
-    fn transaction(&mut self) -> Result<DbTransactionGuard<Self::TxType<'_>>>;
+    /// Begins a transaction using the default [TransactionBehavior::Deferred] start mode.
+    fn transaction(&mut self) -> Result<DbTransactionGuard<'_, Self::TxType<'_>>>;
+
+    /// Begins a transaction using the given [TransactionBehavior] start mode.
+    fn transaction_with(
+        &mut self,
+        behavior: TransactionBehavior,
+    ) -> Result<DbTransactionGuard<'_, Self::TxType<'_>>>;
+
+    /// Runs `f` inside a transaction, committing on `Ok` and rolling back on `Err`, so
+    /// callers no longer have to remember to call [DbTransaction::commit] themselves.
+    /// This always opens a fresh top-level transaction via [TransactionalDb::transaction]
+    /// and has no notion of "already inside a transaction" - calling `run_in_transaction`
+    /// itself from within `f` does not take a [DbTransaction::savepoint], and will
+    /// deadlock/panic for implementations (like [crate::sqlite::SQLiteDbImpl] behind its
+    /// `Rc<RefCell<>>`) that don't support truly concurrent transactions on one
+    /// connection. To nest, call [DbTransaction::run_in_savepoint] on the `tx` passed to
+    /// `f` instead - it's the same commit-on-`Ok`/rollback-on-`Err` combinator, built on
+    /// [DbTransaction::savepoint] rather than a fresh top-level transaction.
+    fn run_in_transaction<'a, T, E, F>(&'a mut self, f: F) -> std::result::Result<T, E>
+    where
+        F: FnOnce(&mut DbTransactionGuard<'a, Self::TxType<'a>>) -> std::result::Result<T, E>,
+        E: From<DbError>,
+    {
+        let mut tx = self.transaction().map_err(E::from)?;
+
+        match f(&mut tx) {
+            Ok(value) => {
+                tx.commit().map_err(E::from)?;
+                Ok(value)
+            }
+            Err(e) => {
+                let _ = tx.rollback();
+                Err(e)
+            }
+        }
+    }
 }
 
 /// Trait for database transactions.
 pub trait DbTransaction<'conn> {
+    /// The concrete transaction type produced by [DbTransaction::savepoint], nested one
+    /// level deeper than `Self`. Implementations typically use the same type recursively,
+    /// allowing savepoints to be nested arbitrarily deep.
+    type SavepointType<'tx>: DbTransaction<'tx>
+    where
+        Self: 'tx;
+
     fn commit(self) -> Result<()>;
     fn rollback(self) -> Result<()>;
+
+    /// Opens a nested, independently-rollbackable scope on top of this still-live
+    /// transaction. Rolling back the returned guard restores state to this savepoint
+    /// without aborting `self`; committing it merges the nested writes into `self`.
+    fn savepoint(&mut self) -> Result<DbTransactionGuard<'_, Self::SavepointType<'_>>>;
+
+    /// Runs `f` inside a [DbTransaction::savepoint] taken on `self`, committing it on
+    /// `Ok` and rolling it back on `Err`. This is [TransactionalDb::run_in_transaction]'s
+    /// nesting counterpart: call it on the `tx` already passed to an outer
+    /// `run_in_transaction`/`run_in_savepoint` closure instead of calling
+    /// `run_in_transaction` again, which would try to open a second top-level
+    /// transaction on the same connection.
+    fn run_in_savepoint<'a, T, E, F>(&'a mut self, f: F) -> std::result::Result<T, E>
+    where
+        F: FnOnce(&mut DbTransactionGuard<'a, Self::SavepointType<'a>>) -> std::result::Result<T, E>,
+        E: From<DbError>,
+    {
+        let mut savepoint = self.savepoint().map_err(E::from)?;
+
+        match f(&mut savepoint) {
+            Ok(value) => {
+                savepoint.commit().map_err(E::from)?;
+                Ok(value)
+            }
+            Err(e) => {
+                let _ = savepoint.rollback();
+                Err(e)
+            }
+        }
+    }
+}
+
+/// Controls what a [DbTransactionGuard] does to its underlying transaction when it is
+/// dropped without an explicit [DbTransaction::commit] or [DbTransaction::rollback].
+/// [DropBehavior::Rollback] is the default; use [DbTransactionGuard::set_drop_behavior]
+/// to pick one of the others for a particular guard.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DropBehavior {
+    /// Roll back the transaction. This is the default.
+    #[default]
+    Rollback,
+    /// Commit the transaction.
+    Commit,
+    /// Neither commit nor roll back explicitly; let the underlying transaction type's
+    /// own `Drop` impl run (for most backends this still rolls back, just without going
+    /// through our error handling).
+    Ignore,
+    /// Panic. Useful in tests/debug builds to catch a forgotten commit/rollback.
+    Panic,
 }
 
 /// Wrapper around a database transaction which stores the transaction.
@@ -72,7 +350,8 @@ pub struct DbTransactionGuard<'conn, TxType>
 where
     TxType: DbTransaction<'conn>,
 {
-    tx: TxType,
+    tx: Option<TxType>,
+    drop_behavior: DropBehavior,
     _phantom: PhantomData<&'conn ()>,
 }
 
@@ -84,10 +363,18 @@ where
 {
     pub fn new(tx: TxType) -> Self {
         Self {
-            tx,
+            tx: Some(tx),
+            drop_behavior: DropBehavior::default(),
             _phantom: PhantomData,
         }
     }
+
+    /// Sets what happens to the underlying transaction if this guard is dropped without
+    /// an explicit [DbTransaction::commit] or [DbTransaction::rollback].
+    #[allow(dead_code)]
+    pub fn set_drop_behavior(&mut self, behavior: DropBehavior) {
+        self.drop_behavior = behavior;
+    }
 }
 
 /// Implementation of [Deref] for [DbTransactionGuard] which which helps keep
@@ -99,7 +386,7 @@ where
     type Target = TxType;
 
     fn deref(&self) -> &Self::Target {
-        &self.tx
+        self.tx.as_ref().expect("transaction already consumed")
     }
 }
 
@@ -109,12 +396,47 @@ impl<'conn, TxType> DbTransaction<'conn> for DbTransactionGuard<'conn, TxType>
 where
     TxType: DbTransaction<'conn>,
 {
-    fn commit(self) -> Result<()> {
-        self.tx.commit()
+    type SavepointType<'tx> = TxType::SavepointType<'tx> where Self: 'tx;
+
+    fn commit(mut self) -> Result<()> {
+        self.tx.take().expect("transaction already consumed").commit()
+    }
+
+    fn rollback(mut self) -> Result<()> {
+        self.tx.take().expect("transaction already consumed").rollback()
     }
 
-    fn rollback(self) -> Result<()> {
-        self.tx.rollback()
+    fn savepoint(&mut self) -> Result<DbTransactionGuard<'_, Self::SavepointType<'_>>> {
+        self.tx.as_mut().expect("transaction already consumed").savepoint()
+    }
+}
+
+/// Applies this guard's [DropBehavior] if the transaction was never explicitly
+/// committed or rolled back. `commit`/`rollback` take the inner transaction out of
+/// the `Option`, so a guard that was already finished is a no-op here.
+impl<'conn, TxType> Drop for DbTransactionGuard<'conn, TxType>
+where
+    TxType: DbTransaction<'conn>,
+{
+    fn drop(&mut self) {
+        let Some(tx) = self.tx.take() else {
+            return;
+        };
+
+        match self.drop_behavior {
+            DropBehavior::Rollback => {
+                let _ = tx.rollback();
+            }
+            DropBehavior::Commit => {
+                let _ = tx.commit();
+            }
+            DropBehavior::Ignore => {
+                drop(tx);
+            }
+            DropBehavior::Panic => {
+                panic!("DbTransactionGuard dropped without an explicit commit() or rollback()");
+            }
+        }
     }
 }
 
@@ -1,6 +1,6 @@
 use std::{cell::RefCell, rc::Rc};
 
-use crate::db::{DbConnectionGuard, DbTransaction, FromDbConnection, Result, TransactionalDb};
+use crate::db::{DbConnectionGuard, FromDbConnection, Result, TransactionalDb};
 
 /// Trait which defines, as an example, "MARF" database operations.
 pub trait MarfTrieDb {
@@ -39,14 +39,12 @@ where
 {
     fn do_something_else_immut(&self) {
         let mut conn = self.conn.borrow_mut();
-        let tx = conn.transaction().unwrap();
+        conn.instrumentation().on_event("marfdb do_something_else_immut");
 
-        eprintln!("marfdb do_something_else_immut");
-
-        tx.commit().unwrap();
+        conn.run_in_transaction(|_tx| -> Result<()> { Ok(()) }).unwrap();
     }
 
     fn do_something_mut(&mut self) {
-        eprintln!("marfdb do_something_mut");
+        self.conn.borrow().instrumentation().on_event("marfdb do_something_mut");
     }
 }
@@ -1,7 +1,7 @@
 use std::cell::RefCell;
 use std::rc::Rc;
 
-use crate::db::{DbConnectionGuard, DbTransaction, FromDbConnection, Result, TransactionalDb};
+use crate::db::{CachingDb, DbConnectionGuard, DbTransaction, FromDbConnection, Result, TransactionalDb};
 
 pub trait SortitionDb {
     fn do_some_mut_thing(&mut self);
@@ -31,18 +31,30 @@ where
 
 impl<DB> SortitionDb for SortitionDbImpl<DB>
 where
-    DB: TransactionalDb,
+    DB: TransactionalDb + CachingDb,
 {
     fn do_some_mut_thing(&mut self) {
         let mut conn = self.conn.borrow_mut();
-        let tx = conn.transaction().unwrap();
-
-        eprintln!("sortdb: do_some_mut_thing");
-
-        tx.commit().unwrap();
+        conn.instrumentation().on_event("sortdb: do_some_mut_thing");
+
+        conn.run_in_transaction(|tx| -> Result<()> {
+            // Runs under a savepoint: anything written in it is discarded on `Err`
+            // without aborting the rest of the transaction (this example closure
+            // writes nothing and returns `Ok`). The savepoint's
+            // own begin/commit is already reported via [Instrumentation::on_begin_transaction]
+            // and [Instrumentation::on_commit], so there's no separate event to fire here.
+            tx.run_in_savepoint(|_sp| -> Result<()> { Ok(()) })
+        })
+        .unwrap();
     }
 
     fn do_some_immut_thing(&self) {
-        eprintln!("sortdb: do_some_immut_thing");
+        let conn = self.conn.borrow();
+
+        // Reuses a cached prepared statement for this connection instead of
+        // re-preparing it on every lookup.
+        let _stmt = conn
+            .prepare_cached("SELECT block_height FROM snapshots WHERE block_height = ?1")
+            .unwrap();
     }
 }
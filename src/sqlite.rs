@@ -1,13 +1,16 @@
 use rusqlite::Connection;
 
-use crate::{
-    DbConnection, TransactionalDb, DbTransactionGuard, DbTransaction, DbError, 
-    Result, DbConnectionGuard
+use crate::db::{
+    DbConnection, TransactionalDb, TransactionBehavior, DbTransactionGuard, DbTransaction,
+    DbError, Result, DbConnectionGuard, SimpleConnection, CachingDb, Instrumentation,
+    NoopInstrumentation
 };
 
 pub struct SQLiteDbImpl {
+    #[allow(dead_code)]
     pub params: SQLiteDbParams,
-    pub conn: Connection
+    pub conn: Connection,
+    instrumentation: Box<dyn Instrumentation<SQLiteDbImpl>>,
 }
 
 #[derive(Clone)]
@@ -16,52 +19,446 @@ pub struct SQLiteDbParams {
 }
 
 
-impl<'conn> DbConnection for SQLiteDbImpl {
+impl DbConnection for SQLiteDbImpl {
     type Params = SQLiteDbParams;
-    fn establish(params: SQLiteDbParams) -> Result<crate::DbConnectionGuard<Self>> where Self: DbConnection {
+    fn establish(params: SQLiteDbParams) -> Result<DbConnectionGuard<Self>> where Self: DbConnection {
         let conn = Connection::open(params.uri.clone())
             .map_err(|e| DbError::Connection(e.to_string()))?;
 
         let db = SQLiteDbImpl {
             params: params.clone(),
-            conn: conn
+            conn,
+            instrumentation: Box::new(NoopInstrumentation),
         };
 
         Ok(DbConnectionGuard::new(db))
     }
+
+    fn set_instrumentation<I>(&mut self, instrumentation: I)
+    where
+        I: Instrumentation<Self> + 'static,
+    {
+        self.instrumentation = Box::new(instrumentation);
+    }
+
+    fn instrumentation(&self) -> &dyn Instrumentation<Self> {
+        self.instrumentation.as_ref()
+    }
 }
 
 impl TransactionalDb for SQLiteDbImpl {
     type TxType<'conn> = SQLiteDbTransactionImpl<'conn> where Self: 'conn;
 
-    fn transaction<'conn, 'tx>(
+    fn transaction<'conn>(
         &'conn mut self
-    ) -> Result<DbTransactionGuard<Self::TxType<'conn>>> {
-        let inner_tx = self.conn.transaction()
-            .expect("failed to begin transaction");
+    ) -> Result<DbTransactionGuard<'conn, Self::TxType<'conn>>> {
+        self.transaction_with(TransactionBehavior::Deferred)
+    }
+
+    fn transaction_with(
+        &mut self,
+        behavior: TransactionBehavior,
+    ) -> Result<DbTransactionGuard<'_, Self::TxType<'_>>> {
+        self.instrumentation.on_begin_transaction();
+
+        let inner_tx = self.conn.transaction_with_behavior(behavior.into())
+            .map_err(|e| DbError::Transaction(e.to_string()))?;
 
-        let tx = SQLiteDbTransactionImpl { 
-            tx: inner_tx
+        let tx = SQLiteDbTransactionImpl {
+            tx: inner_tx,
+            instrumentation: self.instrumentation.as_ref(),
         };
 
         Ok(DbTransactionGuard::new(tx))
     }
 }
 
+impl SimpleConnection for SQLiteDbImpl {
+    fn batch_execute(&self, sql: &str) -> Result<()> {
+        self.conn.execute_batch(sql)
+            .map_err(|e| DbError::Database(e.to_string()))
+    }
+}
+
+/// Backed by `rusqlite`'s own per-connection LRU statement cache rather than a
+/// hand-rolled one, keyed internally by SQL text and bounded by
+/// `Connection::set_prepared_statement_cache_capacity`.
+impl CachingDb for SQLiteDbImpl {
+    type Statement<'conn> = rusqlite::CachedStatement<'conn> where Self: 'conn;
+
+    fn prepare_cached(&self, sql: &str) -> Result<Self::Statement<'_>> {
+        let start = std::time::Instant::now();
+
+        let stmt = self.conn.prepare_cached(sql)
+            .map_err(|e| DbError::Database(e.to_string()))?;
+
+        self.instrumentation.on_query(sql, start.elapsed());
+        Ok(stmt)
+    }
+
+    fn set_statement_cache_capacity(&mut self, capacity: usize) {
+        self.conn.set_prepared_statement_cache_capacity(capacity);
+    }
+}
+
+impl From<TransactionBehavior> for rusqlite::TransactionBehavior {
+    fn from(behavior: TransactionBehavior) -> Self {
+        match behavior {
+            TransactionBehavior::Deferred => rusqlite::TransactionBehavior::Deferred,
+            TransactionBehavior::Immediate => rusqlite::TransactionBehavior::Immediate,
+            TransactionBehavior::Exclusive => rusqlite::TransactionBehavior::Exclusive,
+        }
+    }
+}
+
 pub struct SQLiteDbTransactionImpl<'conn> {
-    tx: rusqlite::Transaction<'conn>
+    tx: rusqlite::Transaction<'conn>,
+    instrumentation: &'conn dyn Instrumentation<SQLiteDbImpl>,
 }
 
 impl<'conn> DbTransaction<'conn> for SQLiteDbTransactionImpl<'conn> {
+    type SavepointType<'tx> = SQLiteSavepointImpl<'tx> where Self: 'tx;
+
     fn commit(self) -> Result<()> {
         self.tx.commit()
-            .map_err(|e| DbError::Commit(e.to_string()))
+            .map_err(|e| DbError::Commit(e.to_string()))?;
+
+        self.instrumentation.on_commit();
+        Ok(())
     }
 
     fn rollback(self) -> Result<()> {
         self.tx.rollback()
-            .map_err(|e| DbError::Rollback(e.to_string()))
+            .map_err(|e| DbError::Rollback(e.to_string()))?;
+
+        self.instrumentation.on_rollback();
+        Ok(())
+    }
+
+    fn savepoint(&mut self) -> Result<DbTransactionGuard<'_, Self::SavepointType<'_>>> {
+        self.instrumentation.on_begin_transaction();
+
+        let sp = self.tx.savepoint()
+            .map_err(|e| DbError::Transaction(e.to_string()))?;
+
+        Ok(DbTransactionGuard::new(SQLiteSavepointImpl {
+            sp,
+            instrumentation: self.instrumentation,
+        }))
+    }
+}
+
+/// A nested transaction scope backed by `rusqlite::Savepoint`. Rolling this back
+/// restores state to the point it was opened without affecting the parent
+/// transaction or savepoint; committing it releases the savepoint, merging its
+/// writes into the parent.
+pub struct SQLiteSavepointImpl<'conn> {
+    sp: rusqlite::Savepoint<'conn>,
+    instrumentation: &'conn dyn Instrumentation<SQLiteDbImpl>,
+}
+
+impl<'conn> DbTransaction<'conn> for SQLiteSavepointImpl<'conn> {
+    type SavepointType<'tx> = SQLiteSavepointImpl<'tx> where Self: 'tx;
+
+    fn commit(self) -> Result<()> {
+        self.sp.commit()
+            .map_err(|e| DbError::Commit(e.to_string()))?;
+
+        self.instrumentation.on_commit();
+        Ok(())
+    }
+
+    fn rollback(mut self) -> Result<()> {
+        self.sp.rollback()
+            .map_err(|e| DbError::Rollback(e.to_string()))?;
+
+        self.instrumentation.on_rollback();
+        Ok(())
+    }
+
+    fn savepoint(&mut self) -> Result<DbTransactionGuard<'_, Self::SavepointType<'_>>> {
+        self.instrumentation.on_begin_transaction();
+
+        let sp = self.sp.savepoint()
+            .map_err(|e| DbError::Transaction(e.to_string()))?;
+
+        Ok(DbTransactionGuard::new(SQLiteSavepointImpl {
+            sp,
+            instrumentation: self.instrumentation,
+        }))
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::db::{CachingDb, DbInit, DbTransaction, DropBehavior, TransactionalDb};
+
+    fn memory_db(schema: &str) -> DbConnectionGuard<SQLiteDbImpl> {
+        SQLiteDbImpl::init(SQLiteDbParams { uri: ":memory:".to_string() }, Some(schema)).unwrap()
+    }
+
+    /// Records the establish, begin/commit/rollback and query [Instrumentation] events
+    /// instead of printing them, so tests can assert on what fired and in what order
+    /// (`on_event` labels are not recorded). Holds its log behind an `Rc<RefCell<>>` so a clone
+    /// can be attached to a connection via [DbConnection::set_instrumentation] while the
+    /// original stays in the test to read it back.
+    #[derive(Clone, Default)]
+    struct SpyInstrumentation {
+        events: Rc<RefCell<Vec<String>>>,
+    }
+
+    impl SpyInstrumentation {
+        fn events(&self) -> Vec<String> {
+            self.events.borrow().clone()
+        }
+    }
+
+    impl<DB> Instrumentation<DB> for SpyInstrumentation
+    where
+        DB: DbConnection,
+    {
+        fn on_establish(&self, _params: &DB::Params) {
+            self.events.borrow_mut().push("establish".to_string());
+        }
+
+        fn on_begin_transaction(&self) {
+            self.events.borrow_mut().push("begin_transaction".to_string());
+        }
+
+        fn on_commit(&self) {
+            self.events.borrow_mut().push("commit".to_string());
+        }
 
+        fn on_rollback(&self) {
+            self.events.borrow_mut().push("rollback".to_string());
+        }
+
+        fn on_query(&self, sql: &str, _duration: Duration) {
+            self.events.borrow_mut().push(format!("query:{sql}"));
+        }
+    }
+
+    fn row_count(db: &DbConnectionGuard<SQLiteDbImpl>) -> i64 {
+        db.db
+            .borrow()
+            .conn
+            .query_row("SELECT COUNT(*) FROM t", [], |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn savepoint_rollback_discards_only_its_own_writes() {
+        let db = memory_db("CREATE TABLE t (v INTEGER NOT NULL);");
+        let mut conn = db.db.borrow_mut();
+
+        let mut tx = conn.transaction().unwrap();
+        tx.tx.execute("INSERT INTO t (v) VALUES (1)", []).unwrap();
+
+        let sp = tx.savepoint().unwrap();
+        sp.sp.execute("INSERT INTO t (v) VALUES (2)", []).unwrap();
+        sp.rollback().unwrap();
+
+        tx.commit().unwrap();
+        drop(conn);
+
+        assert_eq!(row_count(&db), 1);
+    }
+
+    #[test]
+    fn dropped_guard_rolls_back_by_default() {
+        let db = memory_db("CREATE TABLE t (v INTEGER NOT NULL);");
+        {
+            let mut conn = db.db.borrow_mut();
+            let tx = conn.transaction().unwrap();
+            tx.tx.execute("INSERT INTO t (v) VALUES (1)", []).unwrap();
+            // `tx` is dropped here without an explicit commit()/rollback().
+        }
+
+        assert_eq!(row_count(&db), 0);
+    }
+
+    #[test]
+    fn dropped_guard_commits_with_drop_behavior_commit() {
+        let db = memory_db("CREATE TABLE t (v INTEGER NOT NULL);");
+        {
+            let mut conn = db.db.borrow_mut();
+            let mut tx = conn.transaction().unwrap();
+            tx.tx.execute("INSERT INTO t (v) VALUES (1)", []).unwrap();
+            tx.set_drop_behavior(DropBehavior::Commit);
+        }
+
+        assert_eq!(row_count(&db), 1);
+    }
+
+    #[test]
+    fn dropped_guard_with_drop_behavior_ignore_still_rolls_back() {
+        let db = memory_db("CREATE TABLE t (v INTEGER NOT NULL);");
+        {
+            let mut conn = db.db.borrow_mut();
+            let mut tx = conn.transaction().unwrap();
+            tx.tx.execute("INSERT INTO t (v) VALUES (1)", []).unwrap();
+            tx.set_drop_behavior(DropBehavior::Ignore);
+            // Falls through to rusqlite::Transaction's own Drop impl, which also
+            // rolls back an uncommitted transaction - just without going through
+            // our error handling.
+        }
+
+        assert_eq!(row_count(&db), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "dropped without an explicit commit() or rollback()")]
+    fn dropped_guard_panics_with_drop_behavior_panic() {
+        let db = memory_db("CREATE TABLE t (v INTEGER NOT NULL);");
+        let mut conn = db.db.borrow_mut();
+        let mut tx = conn.transaction().unwrap();
+        tx.set_drop_behavior(DropBehavior::Panic);
+        drop(tx);
+    }
+
+    #[test]
+    fn run_in_transaction_commits_on_ok() {
+        let db = memory_db("CREATE TABLE t (v INTEGER NOT NULL);");
+        let mut conn = db.db.borrow_mut();
+
+        conn.run_in_transaction(|tx| -> Result<()> {
+            tx.tx.execute("INSERT INTO t (v) VALUES (1)", []).unwrap();
+            Ok(())
+        })
+        .unwrap();
+
+        drop(conn);
+        assert_eq!(row_count(&db), 1);
+    }
+
+    #[test]
+    fn run_in_transaction_rolls_back_on_err() {
+        let db = memory_db("CREATE TABLE t (v INTEGER NOT NULL);");
+        let mut conn = db.db.borrow_mut();
+
+        let result = conn.run_in_transaction(|tx| -> Result<()> {
+            tx.tx.execute("INSERT INTO t (v) VALUES (1)", []).unwrap();
+            Err(DbError::Other("speculative write was wrong".to_string()))
+        });
+
+        assert!(result.is_err());
+        drop(conn);
+        assert_eq!(row_count(&db), 0);
+    }
+
+    #[test]
+    fn run_in_savepoint_commits_on_ok() {
+        let db = memory_db("CREATE TABLE t (v INTEGER NOT NULL);");
+        let mut conn = db.db.borrow_mut();
+
+        conn.run_in_transaction(|tx| -> Result<()> {
+            tx.run_in_savepoint(|sp| -> Result<()> {
+                sp.sp.execute("INSERT INTO t (v) VALUES (1)", []).unwrap();
+                Ok(())
+            })
+        })
+        .unwrap();
+
+        drop(conn);
+        assert_eq!(row_count(&db), 1);
+    }
+
+    #[test]
+    fn run_in_savepoint_rolls_back_on_err_without_aborting_parent() {
+        let db = memory_db("CREATE TABLE t (v INTEGER NOT NULL);");
+        let mut conn = db.db.borrow_mut();
+
+        conn.run_in_transaction(|tx| -> Result<()> {
+            tx.tx.execute("INSERT INTO t (v) VALUES (1)", []).unwrap();
+
+            let result = tx.run_in_savepoint(|sp| -> Result<()> {
+                sp.sp.execute("INSERT INTO t (v) VALUES (2)", []).unwrap();
+                Err(DbError::Other("speculative write was wrong".to_string()))
+            });
+            assert!(result.is_err());
+
+            Ok(())
+        })
+        .unwrap();
+
+        drop(conn);
+        assert_eq!(row_count(&db), 1);
+    }
+
+    #[test]
+    fn prepare_cached_bypasses_cache_when_capacity_is_zero() {
+        let db = memory_db("CREATE TABLE t (v INTEGER NOT NULL);");
+        let mut conn = db.db.borrow_mut();
+        conn.set_statement_cache_capacity(0);
+
+        // With caching disabled, every call prepares a fresh statement - this should
+        // still succeed rather than erroring.
+        for _ in 0..3 {
+            conn.prepare_cached("SELECT v FROM t").unwrap();
+        }
+    }
+
+    #[test]
+    fn prepare_cached_succeeds_after_eviction_under_a_bounded_capacity() {
+        let db = memory_db("CREATE TABLE t (v INTEGER NOT NULL);");
+        let mut conn = db.db.borrow_mut();
+        conn.set_statement_cache_capacity(1);
+
+        // Two distinct statements compete for the single cache slot; preparing the
+        // first one again afterwards should still succeed rather than erroring on
+        // eviction.
+        conn.prepare_cached("SELECT v FROM t").unwrap();
+        conn.prepare_cached("SELECT COUNT(*) FROM t").unwrap();
+        conn.prepare_cached("SELECT v FROM t").unwrap();
+    }
+
+    #[test]
+    fn instrumentation_reports_establish_query_and_transaction_lifecycle_in_order() {
+        let spy = SpyInstrumentation::default();
+        let db = SQLiteDbImpl::init_with_instrumentation(
+            SQLiteDbParams { uri: ":memory:".to_string() },
+            Some("CREATE TABLE t (v INTEGER NOT NULL);"),
+            spy.clone(),
+        )
+        .unwrap();
+
+        {
+            let conn = db.db.borrow();
+            conn.prepare_cached("SELECT v FROM t").unwrap();
+        }
+
+        {
+            let mut conn = db.db.borrow_mut();
+
+            conn.run_in_transaction(|tx| -> Result<()> {
+                tx.tx.execute("INSERT INTO t (v) VALUES (1)", []).unwrap();
+                Ok(())
+            })
+            .unwrap();
+
+            // A failed closure should report a rollback, never a commit.
+            let result = conn.run_in_transaction(|tx| -> Result<()> {
+                tx.tx.execute("INSERT INTO t (v) VALUES (2)", []).unwrap();
+                Err(DbError::Other("speculative write was wrong".to_string()))
+            });
+            assert!(result.is_err());
+        }
+
+        assert_eq!(
+            spy.events(),
+            vec![
+                "establish".to_string(),
+                "query:SELECT v FROM t".to_string(),
+                "begin_transaction".to_string(),
+                "commit".to_string(),
+                "begin_transaction".to_string(),
+                "rollback".to_string(),
+            ]
+        );
+    }
+}